@@ -0,0 +1,1163 @@
+//! Multidimensional array types, parameterized by a type-level dimension
+//! count `D: Nat`.
+//!
+//! `MDArray<T, D, S>` owns its element storage in `S`, some type
+//! implementing `Storage<T>`; `MDArrayBuf<T, D>` is the common case where
+//! `S` is a heap-allocated `Box<[T]>`, while `MDArrayInline<T, D, S>` keeps
+//! small, fixed-size arrays inline in `S` (typically `[T; N]`) instead.
+//! `MDArrayView<T, D>` borrows the elements of some buffer without owning
+//! them. All of these use row-major layout, as described in the crate root
+//! docs.
+
+use alloc::boxed::Box;
+use alloc::heap;
+use core::fmt::{self, Debug};
+use core::mem;
+use core::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem,
+                RemAssign, Sub, SubAssign};
+use core::ptr;
+use core::slice;
+
+use typenat::{Nat, USIndex};
+
+/// The total number of elements in an array of the given `shape`.
+fn total_len<D: Nat>(shape: &D::IxArray) -> usize {
+    let mut len = 1us;
+    for i in 0..D::value() {
+        len *= unsafe { *shape.us_index_unchecked(i) };
+    }
+    len
+}
+
+/// Converts a multidimensional `index` into a flat row-major offset into a
+/// buffer of the given `shape`, or `None` if any axis of `index` is out of
+/// bounds for `shape`.
+fn checked_offset<D: Nat>(shape: &D::IxArray, index: &D::IxArray) -> Option<usize> {
+    let mut offset = 0us;
+    let mut stride = 1us;
+    for i in (0..D::value()).rev() {
+        let extent = unsafe { *shape.us_index_unchecked(i) };
+        let ix = unsafe { *index.us_index_unchecked(i) };
+        if ix >= extent {
+            return None;
+        }
+        offset += ix * stride;
+        stride *= extent;
+    }
+    Some(offset)
+}
+
+/// Like `checked_offset`, but does not verify that `index` is in bounds for
+/// `shape`. Calling this with an out-of-bounds index is undefined behavior
+/// for callers that use the result for pointer arithmetic.
+unsafe fn unchecked_offset<D: Nat>(shape: &D::IxArray, index: &D::IxArray) -> usize {
+    let mut offset = 0us;
+    let mut stride = 1us;
+    for i in (0..D::value()).rev() {
+        let extent = *shape.us_index_unchecked(i);
+        let ix = *index.us_index_unchecked(i);
+        offset += ix * stride;
+        stride *= extent;
+    }
+    offset
+}
+
+/// Whether two shapes of the same dimension `D` agree on every axis. `D`
+/// guarantees `a` and `b` have the same number of axes, but not that the
+/// per-axis extents match, so this is what elementwise array/array ops use
+/// in place of comparing flat buffer lengths (which two differently-shaped
+/// buffers can still share, e.g. `[2, 3]` and `[3, 2]`).
+fn shapes_eq<D: Nat>(a: &D::IxArray, b: &D::IxArray) -> bool {
+    (0..D::value()).all(|i| unsafe {
+        *a.us_index_unchecked(i) == *b.us_index_unchecked(i)
+    })
+}
+
+/// Decodes a flat row-major `offset` into a buffer of the given `shape` back
+/// into a multidimensional index, using the shape's strides. The caller must
+/// ensure `offset` is in bounds for `shape`.
+fn decode_offset<D: Nat>(shape: &D::IxArray, mut offset: usize) -> D::IxArray {
+    let mut index = *shape;
+    for i in (0..D::value()).rev() {
+        let extent = unsafe { *shape.us_index_unchecked(i) };
+        unsafe { *index.us_index_unchecked_mut(i) = offset % extent; }
+        offset /= extent;
+    }
+    index
+}
+
+/// The per-axis strides of a fully packed row-major buffer of the given
+/// `shape`: the number of elements to skip along each axis to reach the
+/// next element on that axis. This is what every `MDArrayBuf`/`MDArrayInline`
+/// uses implicitly (via `checked_offset`/`unchecked_offset`), but a strided
+/// view like one produced by `MDArrayView::windows` needs its strides kept
+/// around explicitly, since its own shape is no longer packed within the
+/// buffer it borrows from.
+fn row_major_strides<D: Nat>(shape: &D::IxArray) -> D::IxArray {
+    let mut strides = *shape;
+    let mut stride = 1us;
+    for i in (0..D::value()).rev() {
+        let extent = unsafe { *shape.us_index_unchecked(i) };
+        unsafe { *strides.us_index_unchecked_mut(i) = stride; }
+        stride *= extent;
+    }
+    strides
+}
+
+/// Converts a multidimensional `index` into a flat offset using explicit
+/// per-axis `strides`, rather than assuming `index` is packed in standard
+/// row-major order. Does not check `index` against any shape; the caller
+/// must ensure it is in bounds for whatever buffer `strides` describes.
+fn strided_offset<D: Nat>(strides: &D::IxArray, index: &D::IxArray) -> usize {
+    let mut offset = 0us;
+    for i in 0..D::value() {
+        let stride = unsafe { *strides.us_index_unchecked(i) };
+        let ix = unsafe { *index.us_index_unchecked(i) };
+        offset += ix * stride;
+    }
+    offset
+}
+
+/// Like `strided_offset`, but first checks that `index` is in bounds for
+/// `shape`, returning `None` if any axis is out of range.
+fn checked_strided_offset<D: Nat>(shape: &D::IxArray, strides: &D::IxArray, index: &D::IxArray)
+    -> Option<usize>
+{
+    for i in 0..D::value() {
+        let extent = unsafe { *shape.us_index_unchecked(i) };
+        let ix = unsafe { *index.us_index_unchecked(i) };
+        if ix >= extent {
+            return None;
+        }
+    }
+    Some(strided_offset::<D>(strides, index))
+}
+
+/// Computes, for each axis, how many positions a `window_shape`-sized window
+/// can be anchored at within a buffer of the given `shape`: one more than
+/// the last offset at which the window still fits, or zero if the window is
+/// wider than `shape` along that axis (so it can never fit, anywhere).
+fn window_anchor_counts<D: Nat>(shape: &D::IxArray, window_shape: &D::IxArray) -> D::IxArray {
+    let mut counts = *shape;
+    for i in 0..D::value() {
+        let extent = unsafe { *shape.us_index_unchecked(i) };
+        let w = unsafe { *window_shape.us_index_unchecked(i) };
+        unsafe {
+            *counts.us_index_unchecked_mut(i) = if w > extent { 0us } else { extent - w + 1 };
+        }
+    }
+    counts
+}
+
+/// Allocates a buffer of `len` elements, calling `f` with each element's
+/// flat offset to produce its value and writing the result directly in
+/// place. No element is default-constructed or read before `f` fills it in.
+///
+/// If `f` panics partway through, the elements written so far are dropped
+/// and the backing allocation is freed.
+///
+/// `f` is called exactly `len` times even when `T` is zero-sized, matching
+/// `core::array::from_fn`; in that case there is no allocation or storage
+/// to write into, so each result is simply dropped in place.
+fn boxed_from_fn<T, F>(len: usize, mut f: F) -> Box<[T]> where F: FnMut(usize) -> T {
+    if mem::size_of::<T>() == 0 {
+        for i in 0..len {
+            f(i);
+        }
+        return unsafe {
+            mem::transmute(slice::from_raw_parts_mut(heap::EMPTY as *mut T, len))
+        };
+    }
+    if len == 0 {
+        return unsafe {
+            mem::transmute(slice::from_raw_parts_mut(heap::EMPTY as *mut T, len))
+        };
+    }
+
+    let align = mem::align_of::<T>();
+    let size = mem::size_of::<T>().checked_mul(len)
+                   .expect("MDArrayBuf: size overflow");
+    let raw = unsafe { heap::allocate(size, align) } as *mut T;
+    if raw.is_null() {
+        panic!("MDArrayBuf: allocation failure");
+    }
+
+    // Frees `ptr` and drops the first `filled` elements written into it if
+    // we unwind out of the loop below before every element is initialized.
+    struct Guard<T> {
+        ptr: *mut T,
+        len: usize,
+        filled: usize,
+    }
+    #[unsafe_destructor]
+    impl<T> Drop for Guard<T> {
+        fn drop(&mut self) {
+            unsafe {
+                for i in 0..self.filled {
+                    ptr::read(self.ptr.offset(i as isize));
+                }
+                heap::deallocate(self.ptr as *mut u8,
+                                  self.len * mem::size_of::<T>(),
+                                  mem::align_of::<T>());
+            }
+        }
+    }
+
+    let mut guard = Guard { ptr: raw, len: len, filled: 0 };
+    for i in 0..len {
+        let value = f(i);
+        unsafe { ptr::write(guard.ptr.offset(i as isize), value); }
+        guard.filled += 1;
+    }
+    let ptr = guard.ptr;
+    mem::forget(guard);
+    unsafe { mem::transmute(slice::from_raw_parts_mut(ptr, len)) }
+}
+
+/// Breaks a boxed slice into its raw pointer and length without running its
+/// `Drop` glue, handing responsibility for the elements and (if it was
+/// really heap-allocated by `boxed_from_fn`) the backing storage to the
+/// caller.
+fn into_raw_parts<T>(b: Box<[T]>) -> (*mut T, usize) {
+    let len = b.len();
+    let ptr = b.as_ptr() as *mut T;
+    mem::forget(b);
+    (ptr, len)
+}
+
+/// Storage that can back an `MDArray`'s elements: either a heap allocation
+/// (`Box<[T]>`, used by `MDArrayBuf`) or inline storage (`[T; N]`, used by
+/// `MDArrayInline`), following the `Array` trait pattern used by the
+/// `arrayvec` crate.
+///
+/// # Safety
+/// Implementors must ensure `as_ptr`/`as_mut_ptr` return a pointer to (at
+/// least) `capacity()` contiguous, properly aligned elements of `T`.
+pub unsafe trait Storage<T> {
+    /// Pointer to the first element of this storage.
+    fn as_ptr(&self) -> *const T;
+    /// Mutable pointer to the first element of this storage.
+    fn as_mut_ptr(&mut self) -> *mut T;
+    /// The number of elements this storage holds.
+    fn capacity(&self) -> usize;
+}
+
+unsafe impl<T> Storage<T> for Box<[T]> {
+    #[inline]
+    fn as_ptr(&self) -> *const T { (**self).as_ptr() }
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut T { (**self).as_mut_ptr() }
+    #[inline]
+    fn capacity(&self) -> usize { self.len() }
+}
+
+macro_rules! array_storage_impl {
+    ($($n:expr),+) => { $(
+        unsafe impl<T> Storage<T> for [T; $n] {
+            #[inline]
+            fn as_ptr(&self) -> *const T { (&self[..]).as_ptr() }
+            #[inline]
+            fn as_mut_ptr(&mut self) -> *mut T { (&mut self[..]).as_mut_ptr() }
+            #[inline]
+            fn capacity(&self) -> usize { $n }
+        }
+
+        impl<T, D: Nat> MDArrayInline<T, D, [T; $n]> {
+            /// Creates an inline array of the given `shape`, calling `f`
+            /// once per element to produce its value, exactly like
+            /// `MDArrayBuf::from_fn` but writing into `[T; $n]` in place
+            /// instead of allocating a heap buffer.
+            ///
+            /// `shape`'s element count must equal `$n`, which is checked
+            /// with a debug assertion: without const generics, there's no
+            /// way to tie `D`'s shape to a fixed array length at compile
+            /// time.
+            pub fn from_fn<F>(shape: D::IxArray, mut f: F) -> MDArrayInline<T, D, [T; $n]>
+                where F: FnMut(D::IxArray) -> T
+            {
+                debug_assert_eq!(total_len::<D>(&shape), $n,
+                                  "MDArrayInline: shape/storage capacity mismatch");
+
+                // Fill an `[Option<T>; $n]` rather than a bare `[T; $n]`: the
+                // array itself (not some separate guard) is what the
+                // compiler drops if `f` panics partway through, and it drops
+                // every slot unconditionally, regardless of how much of the
+                // array our raw writes have actually reached. Starting every
+                // slot at `None` means that unconditional drop is always
+                // safe and correct on its own — already-filled slots are
+                // `Some` and drop their `T` normally, not-yet-filled slots
+                // are `None` and drop as a no-op — with no separate guard
+                // type needed to track how far the loop got.
+                let mut data: [Option<T>; $n] = unsafe {
+                    let mut slots: [Option<T>; $n] = mem::uninitialized();
+                    for slot in slots.iter_mut() {
+                        ptr::write(slot, None);
+                    }
+                    slots
+                };
+                for offset in 0..$n {
+                    let ix = decode_offset::<D>(&shape, offset);
+                    data[offset] = Some(f(ix));
+                }
+
+                // Every slot is `Some` at this point, so draining them into
+                // the real `[T; $n]` storage can't observe a `None`. Moving
+                // each value out with `ptr::read` and then forgetting `data`
+                // (rather than letting it drop normally) hands the elements
+                // to `out` without ever running their destructors twice.
+                let out = unsafe {
+                    let mut out: [T; $n] = mem::uninitialized();
+                    for i in 0..$n {
+                        let value = ptr::read(&data[i]).expect("MDArrayInline: from_fn slot left empty");
+                        ptr::write(&mut out[i], value);
+                    }
+                    mem::forget(data);
+                    out
+                };
+                MDArray { shape: shape, data: out }
+            }
+        }
+
+        impl<T: Clone, D: Nat> MDArrayInline<T, D, [T; $n]> {
+            /// Creates an inline array of the given `shape`, filling every
+            /// element with a clone of `elem`.
+            pub fn from_elem(shape: D::IxArray, elem: T) -> MDArrayInline<T, D, [T; $n]> {
+                Self::from_fn(shape, |_| elem.clone())
+            }
+        }
+        )+
+    }
+}
+
+array_storage_impl!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+                     22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32);
+
+/// An owning multidimensional array with `D` axes, backed by some `Storage`
+/// laid out in row-major order.
+#[derive(Debug)]
+pub struct MDArray<T, D: Nat, S: Storage<T>> {
+    shape: D::IxArray,
+    data: S,
+}
+
+/// An owning multidimensional array with `D` axes, backed by heap-allocated
+/// storage.
+pub type MDArrayBuf<T, D> = MDArray<T, D, Box<[T]>>;
+
+/// A small, fixed-capacity multidimensional array whose elements live
+/// inline in `S` (typically `[T; N]`) instead of behind a heap allocation,
+/// so small matrices used in tight loops (e.g. 3x3, 4x4) avoid allocation
+/// and indirection entirely while sharing the same `MDArrayView` indexing
+/// code as `MDArrayBuf`.
+pub type MDArrayInline<T, D, S> = MDArray<T, D, S>;
+
+impl<T, D: Nat, S: Storage<T>> MDArray<T, D, S> {
+    /// The per-axis extent of this array.
+    #[inline]
+    pub fn shape(&self) -> D::IxArray {
+        self.shape
+    }
+
+    /// The total number of elements in this array.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Borrows the element at `index`, or returns `None` if `index` is out
+    /// of bounds for this array's shape.
+    pub fn get(&self, index: D::IxArray) -> Option<&T> {
+        checked_offset::<D>(&self.shape, &index)
+            .map(|off| unsafe { &*self.data.as_ptr().offset(off as isize) })
+    }
+
+    /// Mutably borrows the element at `index`, or returns `None` if `index`
+    /// is out of bounds for this array's shape.
+    pub fn get_mut(&mut self, index: D::IxArray) -> Option<&mut T> {
+        checked_offset::<D>(&self.shape, &index)
+            .map(move |off| unsafe { &mut *self.data.as_mut_ptr().offset(off as isize) })
+    }
+
+    /// Borrows this array's elements as a read-only view.
+    pub fn view(&self) -> MDArrayView<T, D> {
+        let data = unsafe { slice::from_raw_parts(self.data.as_ptr(), self.data.capacity()) };
+        MDArrayView { shape: self.shape, strides: row_major_strides::<D>(&self.shape), data: data }
+    }
+
+    /// Iterates over every `window_shape`-sized sub-view of this array, in
+    /// row-major order of the window's anchor position. See
+    /// `MDArrayView::windows` for the details.
+    pub fn windows(&self, window_shape: D::IxArray) -> Windows<T, D> {
+        self.view().windows(window_shape)
+    }
+
+    /// Reduces every `window_shape`-sized window of this array to a single
+    /// element with `f`, collecting the results into a smaller output array.
+    /// See `MDArrayView::map_windows` for the details.
+    pub fn map_windows<U, F>(&self, window_shape: D::IxArray, f: F) -> MDArrayBuf<U, D>
+        where F: FnMut(MDArrayView<T, D>) -> U
+    {
+        self.view().map_windows(window_shape, f)
+    }
+}
+
+impl<T, D: Nat> MDArrayBuf<T, D> {
+    /// Creates an array of the given `shape`, calling `f` once for every
+    /// element to produce its value. `f` receives the element's full
+    /// multidimensional index, decoded from the row-major offset being
+    /// filled, analogous to `core::array::from_fn`.
+    ///
+    /// Offsets are visited in row-major order and written in place as they
+    /// are produced, so (unlike building via `from_elem` and then assigning
+    /// over it in a second pass) no element is ever constructed twice.
+    pub fn from_fn<F>(shape: D::IxArray, mut f: F) -> MDArrayBuf<T, D>
+        where F: FnMut(D::IxArray) -> T
+    {
+        let len = total_len::<D>(&shape);
+        let data = boxed_from_fn(len, |offset| f(decode_offset::<D>(&shape, offset)));
+        MDArrayBuf { shape: shape, data: data }
+    }
+
+    /// Borrows several elements of this array mutably at once, given their
+    /// indices. Returns `None` if any index is out of bounds for this
+    /// array's shape, or if any two indices name the same element.
+    ///
+    /// This mirrors the slice `get_many_mut` API for a multidimensional
+    /// array: the borrow checker cannot see that the requested elements are
+    /// disjoint, so this checks that explicitly (converting every index to
+    /// its flat offset, then doing the usual `K*(K-1)/2` pairwise comparison
+    /// of those offsets) before building the references via raw pointer
+    /// arithmetic.
+    pub fn get_many_mut<'a>(&'a mut self, indices: &[D::IxArray])
+        -> Option<Box<[&'a mut T]>>
+    {
+        let shape = self.shape;
+        let mut in_bounds = true;
+        let offsets: Box<[usize]> = boxed_from_fn(indices.len(), |i| {
+            match checked_offset::<D>(&shape, &indices[i]) {
+                Some(off) => off,
+                None => { in_bounds = false; 0us }
+            }
+        });
+        if !in_bounds {
+            return None;
+        }
+        for i in 0..offsets.len() {
+            for j in (i + 1)..offsets.len() {
+                if offsets[i] == offsets[j] {
+                    return None;
+                }
+            }
+        }
+        Some(unsafe { self.deref_offsets_mut(&offsets) })
+    }
+
+    /// Like `get_many_mut`, but does not check that `indices` are in bounds
+    /// for this array's shape or that they are pairwise distinct. Calling
+    /// this with an out-of-bounds or duplicate index is undefined behavior.
+    pub unsafe fn get_many_unchecked_mut<'a>(&'a mut self, indices: &[D::IxArray])
+        -> Box<[&'a mut T]>
+    {
+        let shape = self.shape;
+        let offsets: Box<[usize]> =
+            boxed_from_fn(indices.len(), |i| unchecked_offset::<D>(&shape, &indices[i]));
+        self.deref_offsets_mut(&offsets)
+    }
+
+    /// Builds references to the elements at `offsets`, which the caller must
+    /// guarantee are in bounds and pairwise distinct.
+    unsafe fn deref_offsets_mut<'a>(&'a mut self, offsets: &[usize]) -> Box<[&'a mut T]> {
+        let ptr = self.data.as_mut_ptr();
+        boxed_from_fn(offsets.len(), |i| &mut *ptr.offset(offsets[i] as isize))
+    }
+}
+
+impl<T: Clone, D: Nat> MDArrayBuf<T, D> {
+    /// Creates an array of the given `shape`, filling every element with a
+    /// clone of `elem`.
+    pub fn from_elem(shape: D::IxArray, elem: T) -> MDArrayBuf<T, D> {
+        MDArrayBuf::from_fn(shape, |_| elem.clone())
+    }
+}
+
+impl<T, D: Nat, S: Storage<T>> Index<D::IxArray> for MDArray<T, D, S> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, index: D::IxArray) -> &T {
+        self.get(index).expect("MDArray: index out of bounds")
+    }
+}
+
+impl<T, D: Nat, S: Storage<T>> IndexMut<D::IxArray> for MDArray<T, D, S> {
+    #[inline]
+    fn index_mut(&mut self, index: D::IxArray) -> &mut T {
+        self.get_mut(index).expect("MDArray: index out of bounds")
+    }
+}
+
+/// A borrowed view of the elements of some `MDArrayBuf`, sharing its shape
+/// and row-major layout without owning the storage.
+///
+/// `strides` lets a view's own shape disagree with how its elements are
+/// actually laid out in `data`: for a view produced by `MDArray::view`, it's
+/// just the packed row-major strides of `shape`, but for a sub-view
+/// produced by `windows` it carries over the *parent's* strides instead, so
+/// indexing into the sub-view reads straight through to the right elements
+/// of the buffer the parent borrowed from, without copying.
+#[derive(Debug)]
+pub struct MDArrayView<'a, T: 'a, D: Nat> {
+    shape: D::IxArray,
+    strides: D::IxArray,
+    data: &'a [T],
+}
+
+impl<'a, T, D: Nat> MDArrayView<'a, T, D> {
+    /// The per-axis extent of this view.
+    #[inline]
+    pub fn shape(&self) -> D::IxArray {
+        self.shape
+    }
+
+    /// The total number of elements visible through this view.
+    #[inline]
+    pub fn len(&self) -> usize {
+        total_len::<D>(&self.shape)
+    }
+
+    /// Borrows the element at `index`, or returns `None` if `index` is out
+    /// of bounds for this view's shape.
+    pub fn get(&self, index: D::IxArray) -> Option<&T> {
+        checked_strided_offset::<D>(&self.shape, &self.strides, &index).map(|off| &self.data[off])
+    }
+
+    /// Iterates over this view's elements in row-major order.
+    pub fn iter(&self) -> Iter<'a, T, D> {
+        Iter { data: self.data, shape: self.shape, strides: self.strides, pos: 0, len: self.len() }
+    }
+
+    /// Iterates over every `window_shape`-sized sub-view of this view, in
+    /// row-major order of the window's anchor (top-left corner) position.
+    ///
+    /// Each window aliases this view's own buffer rather than copying it,
+    /// via `strides` carried over from this view: that's what lets
+    /// `windows` support convolution, pooling, and finite-difference
+    /// stencils without any per-window allocation. Windows are
+    /// bounds-clamped, in the sense that `windows` never yields a window
+    /// that would reach outside this view: if `window_shape` doesn't fit
+    /// within `shape` along some axis, the iterator yields no windows at
+    /// all rather than panicking or producing an out-of-range one.
+    ///
+    /// There's no mutable counterpart: consecutive windows overlap (by
+    /// construction, since they advance one step at a time), so handing out
+    /// `&mut` windows would let two live views alias the same elements, the
+    /// same reason the standard library doesn't provide `[T]::windows_mut`.
+    /// In-place stencil writes should go through `MDArray::get_many_mut`
+    /// (or plain indexing) instead.
+    pub fn windows(&self, window_shape: D::IxArray) -> Windows<'a, T, D> {
+        let anchor_counts = window_anchor_counts::<D>(&self.shape, &window_shape);
+        Windows {
+            data: self.data,
+            strides: self.strides,
+            window_shape: window_shape,
+            anchor_counts: anchor_counts,
+            next: 0,
+            total: total_len::<D>(&anchor_counts),
+        }
+    }
+
+    /// Reduces every `window_shape`-sized window of this view to a single
+    /// element with `f`, collecting the results into a smaller output array
+    /// whose shape is `window_anchor_counts(self.shape(), window_shape)`
+    /// (one element per valid window anchor). This is the `windows`
+    /// counterpart to `Iterator::map`, for convolution/pooling/stencil
+    /// reductions like sums, maxima, or dot products against a kernel.
+    pub fn map_windows<U, F>(&self, window_shape: D::IxArray, mut f: F) -> MDArrayBuf<U, D>
+        where F: FnMut(MDArrayView<T, D>) -> U
+    {
+        let mut windows = self.windows(window_shape);
+        let out_shape = window_anchor_counts::<D>(&self.shape, &window_shape);
+        MDArrayBuf::from_fn(out_shape, |_| {
+            f(windows.next().expect("MDArrayView: map_windows ran out of windows"))
+        })
+    }
+}
+
+impl<'a, T, D: Nat> Index<D::IxArray> for MDArrayView<'a, T, D> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, index: D::IxArray) -> &T {
+        self.get(index).expect("MDArrayView: index out of bounds")
+    }
+}
+
+/// A row-major iterator over the borrowed elements of an `MDArrayView`,
+/// produced by `MDArrayView::iter`. Walks indices rather than a single
+/// contiguous slice, so this also works for the non-contiguous sub-views
+/// produced by `windows`.
+pub struct Iter<'a, T: 'a, D: Nat> {
+    data: &'a [T],
+    shape: D::IxArray,
+    strides: D::IxArray,
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, T, D: Nat> Iterator for Iter<'a, T, D> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.pos == self.len {
+            None
+        } else {
+            let ix = decode_offset::<D>(&self.shape, self.pos);
+            let off = strided_offset::<D>(&self.strides, &ix);
+            self.pos += 1;
+            Some(&self.data[off])
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T, D: Nat> ExactSizeIterator for Iter<'a, T, D> {}
+
+impl<'a, T: Debug, D: Nat> Debug for Iter<'a, T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Iter").field("remaining", &(self.len - self.pos)).finish()
+    }
+}
+
+/// An iterator over the overlapping `window_shape`-sized sub-views of an
+/// `MDArrayView`, advancing one step at a time across every axis in
+/// row-major order of the window's anchor position, as produced by
+/// `MDArrayView::windows`.
+pub struct Windows<'a, T: 'a, D: Nat> {
+    data: &'a [T],
+    strides: D::IxArray,
+    window_shape: D::IxArray,
+    anchor_counts: D::IxArray,
+    next: usize,
+    total: usize,
+}
+
+impl<'a, T, D: Nat> Iterator for Windows<'a, T, D> {
+    type Item = MDArrayView<'a, T, D>;
+
+    fn next(&mut self) -> Option<MDArrayView<'a, T, D>> {
+        if self.next == self.total {
+            None
+        } else {
+            let anchor = decode_offset::<D>(&self.anchor_counts, self.next);
+            let offset = strided_offset::<D>(&self.strides, &anchor);
+            self.next += 1;
+            Some(MDArrayView { shape: self.window_shape, strides: self.strides,
+                                data: &self.data[offset..] })
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T, D: Nat> ExactSizeIterator for Windows<'a, T, D> {}
+
+impl<'a, T, D: Nat> Debug for Windows<'a, T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Windows")
+            .field("window_shape", &self.window_shape)
+            .field("remaining", &(self.total - self.next))
+            .finish()
+    }
+}
+
+// Owning iteration. Views only ever borrow elements, so moving non-`Copy`
+// element types out of an array (to `collect` into something else, or to
+// pipeline into `map`/`zip`) needs its own iterator, analogous to the array
+// `IntoIter` added to `core`.
+impl<T, D: Nat> IntoIterator for MDArrayBuf<T, D> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        let (ptr, len) = into_raw_parts(self.data);
+        IntoIter { ptr: ptr, len: len, start: 0, end: len }
+    }
+}
+
+/// An iterator that moves the elements of an `MDArrayBuf<T, D>` out by
+/// value, in row-major order.
+///
+/// Tracks a front and back cursor so it can be consumed from either end;
+/// any elements not yet yielded when the iterator itself is dropped are
+/// dropped in turn, and the backing storage is freed.
+pub struct IntoIter<T> {
+    ptr: *mut T,
+    len: usize,
+    start: usize,
+    end: usize,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            let item = unsafe { ptr::read(self.ptr.offset(self.start as isize)) };
+            self.start += 1;
+            Some(item)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.start;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { ptr::read(self.ptr.offset(self.end as isize)) })
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T: Debug> Debug for IntoIter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let remaining: &[T] = unsafe {
+            slice::from_raw_parts(self.ptr.offset(self.start as isize), self.end - self.start)
+        };
+        f.debug_tuple("IntoIter").field(&remaining).finish()
+    }
+}
+
+#[unsafe_destructor]
+impl<T> Drop for IntoIter<T> {
+    fn drop(&mut self) {
+        unsafe {
+            for i in self.start..self.end {
+                ptr::read(self.ptr.offset(i as isize));
+            }
+            if self.len != 0 && mem::size_of::<T>() != 0 {
+                heap::deallocate(self.ptr as *mut u8,
+                                  self.len * mem::size_of::<T>(),
+                                  mem::align_of::<T>());
+            }
+        }
+    }
+}
+
+// Element-wise arithmetic, following the approach of the `numeric-array`
+// crate: the standard `ops` traits are forwarded through to each element.
+// The array/array shape match is only a debug assertion, since type-level
+// `D` already guarantees the two operands have the same number of axes, but
+// not that they agree on extent per axis, so the check is on `shape` itself
+// (via `shapes_eq`) rather than on the flat buffer length, which two
+// differently-shaped buffers can still share.
+macro_rules! elementwise_binop {
+    ($Trait:ident, $method:ident, $AssignTrait:ident, $assign_method:ident) => {
+        impl<T, D: Nat> $Trait<MDArrayBuf<T, D>> for MDArrayBuf<T, D>
+            where T: $Trait<Output = T> + Clone
+        {
+            type Output = MDArrayBuf<T, D>;
+
+            fn $method(self, rhs: MDArrayBuf<T, D>) -> MDArrayBuf<T, D> {
+                debug_assert!(shapes_eq::<D>(&self.shape, &rhs.shape),
+                              "MDArrayBuf: shape mismatch in elementwise operation");
+                let shape = self.shape;
+                let data = boxed_from_fn(self.data.len(),
+                    |i| self.data[i].clone().$method(rhs.data[i].clone()));
+                MDArrayBuf { shape: shape, data: data }
+            }
+        }
+
+        impl<T, D: Nat> $Trait<T> for MDArrayBuf<T, D>
+            where T: $Trait<Output = T> + Clone
+        {
+            type Output = MDArrayBuf<T, D>;
+
+            fn $method(self, rhs: T) -> MDArrayBuf<T, D> {
+                let shape = self.shape;
+                let data = boxed_from_fn(self.data.len(),
+                    |i| self.data[i].clone().$method(rhs.clone()));
+                MDArrayBuf { shape: shape, data: data }
+            }
+        }
+
+        impl<T, D: Nat> $AssignTrait<MDArrayBuf<T, D>> for MDArrayBuf<T, D>
+            where T: $Trait<Output = T> + Clone
+        {
+            fn $assign_method(&mut self, rhs: MDArrayBuf<T, D>) {
+                debug_assert!(shapes_eq::<D>(&self.shape, &rhs.shape),
+                              "MDArrayBuf: shape mismatch in elementwise operation");
+                for i in 0..self.data.len() {
+                    let lhs = self.data[i].clone();
+                    self.data[i] = lhs.$method(rhs.data[i].clone());
+                }
+            }
+        }
+
+        impl<T, D: Nat> $AssignTrait<T> for MDArrayBuf<T, D>
+            where T: $Trait<Output = T> + Clone
+        {
+            fn $assign_method(&mut self, rhs: T) {
+                for i in 0..self.data.len() {
+                    let lhs = self.data[i].clone();
+                    self.data[i] = lhs.$method(rhs.clone());
+                }
+            }
+        }
+    }
+}
+
+elementwise_binop!(Add, add, AddAssign, add_assign);
+elementwise_binop!(Sub, sub, SubAssign, sub_assign);
+elementwise_binop!(Mul, mul, MulAssign, mul_assign);
+elementwise_binop!(Div, div, DivAssign, div_assign);
+elementwise_binop!(Rem, rem, RemAssign, rem_assign);
+
+impl<T, D: Nat> Neg for MDArrayBuf<T, D> where T: Neg<Output = T> + Clone {
+    type Output = MDArrayBuf<T, D>;
+
+    fn neg(self) -> MDArrayBuf<T, D> {
+        let shape = self.shape;
+        let data = boxed_from_fn(self.data.len(), |i| -self.data[i].clone());
+        MDArrayBuf { shape: shape, data: data }
+    }
+}
+
+// `T op MDArrayBuf<T, D>` (scalar on the left) can't be written generically
+// over `T`, since neither `Add` nor `T` are local to this crate for an
+// arbitrary `T`; the orphan rules only let us provide it type by type, so we
+// macro it out over the primitive numeric types.
+macro_rules! scalar_lhs_binop {
+    ($($t:ty),+) => { $(
+        impl<D: Nat> Add<MDArrayBuf<$t, D>> for $t {
+            type Output = MDArrayBuf<$t, D>;
+
+            fn add(self, rhs: MDArrayBuf<$t, D>) -> MDArrayBuf<$t, D> {
+                rhs.add(self)
+            }
+        }
+
+        impl<D: Nat> Sub<MDArrayBuf<$t, D>> for $t {
+            type Output = MDArrayBuf<$t, D>;
+
+            fn sub(self, rhs: MDArrayBuf<$t, D>) -> MDArrayBuf<$t, D> {
+                let shape = rhs.shape;
+                let data = boxed_from_fn(rhs.data.len(), |i| self - rhs.data[i].clone());
+                MDArrayBuf { shape: shape, data: data }
+            }
+        }
+
+        impl<D: Nat> Mul<MDArrayBuf<$t, D>> for $t {
+            type Output = MDArrayBuf<$t, D>;
+
+            fn mul(self, rhs: MDArrayBuf<$t, D>) -> MDArrayBuf<$t, D> {
+                rhs.mul(self)
+            }
+        }
+
+        impl<D: Nat> Div<MDArrayBuf<$t, D>> for $t {
+            type Output = MDArrayBuf<$t, D>;
+
+            fn div(self, rhs: MDArrayBuf<$t, D>) -> MDArrayBuf<$t, D> {
+                let shape = rhs.shape;
+                let data = boxed_from_fn(rhs.data.len(), |i| self / rhs.data[i].clone());
+                MDArrayBuf { shape: shape, data: data }
+            }
+        }
+
+        impl<D: Nat> Rem<MDArrayBuf<$t, D>> for $t {
+            type Output = MDArrayBuf<$t, D>;
+
+            fn rem(self, rhs: MDArrayBuf<$t, D>) -> MDArrayBuf<$t, D> {
+                let shape = rhs.shape;
+                let data = boxed_from_fn(rhs.data.len(), |i| self % rhs.data[i].clone());
+                MDArrayBuf { shape: shape, data: data }
+            }
+        }
+        )+
+    }
+}
+
+scalar_lhs_binop!(f32, f64, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+    use typenat::{N0, N2, N3};
+    use super::{MDArrayBuf, MDArrayInline};
+
+    #[test]
+    fn from_fn_fills_ramp_in_row_major_order() {
+        let a: MDArrayBuf<usize, N2> =
+            MDArrayBuf::from_fn([2us, 3us], |ix| ix[0] * 3 + ix[1]);
+        assert_eq!(a.shape(), [2us, 3us]);
+        assert_eq!(a.len(), 6us);
+        for i in 0..2us {
+            for j in 0..3us {
+                assert_eq!(*a.get([i, j]).unwrap(), i * 3 + j);
+            }
+        }
+    }
+
+    #[test]
+    fn from_fn_receives_full_index() {
+        let a: MDArrayBuf<[usize; 3], N3> =
+            MDArrayBuf::from_fn([2us, 2us, 2us], |ix| ix);
+        assert_eq!(*a.get([1us, 0us, 1us]).unwrap(), [1us, 0us, 1us]);
+    }
+
+    #[test]
+    fn from_fn_zero_length_axis_is_empty() {
+        let a: MDArrayBuf<usize, N2> =
+            MDArrayBuf::from_fn([0us, 5us], |_| 0us);
+        assert_eq!(a.len(), 0us);
+    }
+
+    #[test]
+    fn from_fn_calls_f_once_per_index_for_zero_sized_elements() {
+        use std::cell::Cell;
+
+        let calls = Cell::new(0us);
+        let a: MDArrayBuf<(), N2> = MDArrayBuf::from_fn([2us, 3us], |_| {
+            calls.set(calls.get() + 1us);
+        });
+        assert_eq!(calls.get(), 6us);
+        assert_eq!(a.len(), 6us);
+    }
+
+    #[test]
+    fn from_elem_fills_every_slot() {
+        let a: MDArrayBuf<usize, N0> = MDArrayBuf::from_elem([], 7us);
+        assert_eq!(a.len(), 1us);
+        assert_eq!(*a.get([]).unwrap(), 7us);
+    }
+
+    #[test]
+    fn get_out_of_bounds_is_none() {
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_elem([2us, 2us], 0us);
+        assert!(a.get([2us, 0us]).is_none());
+    }
+
+    #[test]
+    fn get_many_mut_swaps_disjoint_elements() {
+        let mut a: MDArrayBuf<usize, N2> =
+            MDArrayBuf::from_fn([2us, 2us], |ix| ix[0] * 2 + ix[1]);
+        {
+            let mut refs = a.get_many_mut(&[[0us, 0us], [1us, 1us]]).unwrap();
+            let tmp = *refs[0];
+            *refs[0] = *refs[1];
+            *refs[1] = tmp;
+        }
+        assert_eq!(*a.get([0us, 0us]).unwrap(), 3us);
+        assert_eq!(*a.get([1us, 1us]).unwrap(), 0us);
+    }
+
+    #[test]
+    fn get_many_mut_rejects_duplicate_indices() {
+        let mut a: MDArrayBuf<usize, N2> = MDArrayBuf::from_elem([2us, 2us], 0us);
+        assert!(a.get_many_mut(&[[0us, 0us], [0us, 0us]]).is_none());
+    }
+
+    #[test]
+    fn get_many_mut_rejects_out_of_bounds_index() {
+        let mut a: MDArrayBuf<usize, N2> = MDArrayBuf::from_elem([2us, 2us], 0us);
+        assert!(a.get_many_mut(&[[0us, 0us], [5us, 0us]]).is_none());
+    }
+
+    #[test]
+    fn add_combines_arrays_elementwise() {
+        let a: MDArrayBuf<i32, N2> = MDArrayBuf::from_fn([2us, 2us], |ix| (ix[0] * 2 + ix[1]) as i32);
+        let b: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([2us, 2us], 10i32);
+        let c = a + b;
+        assert_eq!(*c.get([0us, 0us]).unwrap(), 10i32);
+        assert_eq!(*c.get([1us, 1us]).unwrap(), 13i32);
+    }
+
+    #[test]
+    #[should_panic(expected = "shape mismatch")]
+    fn add_rejects_mismatched_shape_with_equal_len() {
+        let a: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([2us, 3us], 1i32);
+        let b: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([3us, 2us], 1i32);
+        let _ = a + b;
+    }
+
+    #[test]
+    fn mul_by_scalar_broadcasts() {
+        let a: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([2us, 2us], 3i32);
+        let b = a * 4i32;
+        assert_eq!(*b.get([0us, 0us]).unwrap(), 12i32);
+    }
+
+    #[test]
+    fn scalar_minus_array_broadcasts() {
+        let a: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([2us, 2us], 3i32);
+        let b = 10i32 - a;
+        assert_eq!(*b.get([0us, 0us]).unwrap(), 7i32);
+    }
+
+    #[test]
+    fn add_assign_mutates_in_place() {
+        let mut a: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([2us, 2us], 1i32);
+        a += 5i32;
+        assert_eq!(*a.get([0us, 0us]).unwrap(), 6i32);
+    }
+
+    #[test]
+    fn neg_flips_sign_of_every_element() {
+        let a: MDArrayBuf<i32, N2> = MDArrayBuf::from_elem([2us, 2us], 3i32);
+        let b = -a;
+        assert_eq!(*b.get([0us, 0us]).unwrap(), -3i32);
+    }
+
+    #[test]
+    fn into_iter_yields_elements_in_row_major_order() {
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_fn([2us, 2us], |ix| ix[0] * 2 + ix[1]);
+        let v: Vec<usize> = a.into_iter().collect();
+        assert_eq!(&v[..], [0us, 1us, 2us, 3us]);
+    }
+
+    #[test]
+    fn into_iter_is_double_ended() {
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_fn([2us, 2us], |ix| ix[0] * 2 + ix[1]);
+        let mut it = a.into_iter();
+        assert_eq!(it.next(), Some(0us));
+        assert_eq!(it.next_back(), Some(3us));
+        assert_eq!(it.next_back(), Some(2us));
+        assert_eq!(it.next(), Some(1us));
+        assert_eq!(it.next(), None);
+        assert_eq!(it.next_back(), None);
+    }
+
+    #[test]
+    fn into_iter_reports_exact_len() {
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_elem([2us, 2us], 0us);
+        let mut it = a.into_iter();
+        assert_eq!(it.len(), 4us);
+        it.next();
+        assert_eq!(it.len(), 3us);
+    }
+
+    #[test]
+    fn into_iter_drops_remaining_non_copy_elements() {
+        use std::rc::Rc;
+        use std::cell::Cell;
+
+        let count = Rc::new(Cell::new(0us));
+        let a: MDArrayBuf<Rc<Cell<usize>>, N2> =
+            MDArrayBuf::from_elem([2us, 2us], count.clone());
+        let mut it = a.into_iter();
+        let _first = it.next();
+        drop(it);
+        drop(_first);
+        assert_eq!(Rc::strong_count(&count), 1us);
+    }
+
+    #[test]
+    fn inline_from_fn_fills_ramp_in_row_major_order() {
+        let a: MDArrayInline<usize, N2, [usize; 4]> =
+            MDArrayInline::from_fn([2us, 2us], |ix| ix[0] * 2 + ix[1]);
+        assert_eq!(a.shape(), [2us, 2us]);
+        assert_eq!(a.len(), 4us);
+        assert_eq!(*a.get([1us, 0us]).unwrap(), 2us);
+    }
+
+    #[test]
+    fn inline_from_elem_fills_every_slot() {
+        let a: MDArrayInline<i32, N2, [i32; 4]> = MDArrayInline::from_elem([2us, 2us], 9i32);
+        assert_eq!(*a.get([0us, 0us]).unwrap(), 9i32);
+        assert_eq!(*a.get([1us, 1us]).unwrap(), 9i32);
+    }
+
+    #[test]
+    fn inline_view_shares_indexing_with_buf() {
+        let a: MDArrayInline<usize, N2, [usize; 4]> =
+            MDArrayInline::from_fn([2us, 2us], |ix| ix[0] * 2 + ix[1]);
+        let view = a.view();
+        assert_eq!(*view.get([1us, 1us]).unwrap(), 3us);
+    }
+
+    #[test]
+    fn inline_from_fn_drops_only_the_filled_prefix_on_panic() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1us, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0us));
+        let counted = dropped.clone();
+        let result = thread::spawn(move || {
+            let _a: MDArrayInline<DropCounter, N2, [DropCounter; 4]> =
+                MDArrayInline::from_fn([2us, 2us], |ix| {
+                    if ix == [1us, 0us] {
+                        panic!("boom");
+                    }
+                    DropCounter(counted.clone())
+                });
+        }).join();
+
+        assert!(result.is_err());
+        // Only the two elements actually written before the panic (offsets
+        // 0 and 1) should have been dropped, once each — not the
+        // not-yet-written tail, and not twice over.
+        assert_eq!(dropped.load(Ordering::SeqCst), 2us);
+    }
+
+    #[test]
+    fn iter_yields_view_elements_in_row_major_order() {
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_fn([2us, 3us], |ix| ix[0] * 3 + ix[1]);
+        let v: Vec<usize> = a.view().iter().map(|&x| x).collect();
+        assert_eq!(&v[..], [0us, 1us, 2us, 3us, 4us, 5us]);
+    }
+
+    #[test]
+    fn windows_walk_anchors_in_row_major_order() {
+        // 2x3 ramp: [[0, 1, 2], [3, 4, 5]]. 2x2 windows have two valid
+        // anchors: (0, 0) and (0, 1).
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_fn([2us, 3us], |ix| ix[0] * 3 + ix[1]);
+        let windows: Vec<Vec<usize>> =
+            a.windows([2us, 2us]).map(|w| w.iter().map(|&x| x).collect()).collect();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(&windows[0][..], [0us, 1us, 3us, 4us]);
+        assert_eq!(&windows[1][..], [1us, 2us, 4us, 5us]);
+    }
+
+    #[test]
+    fn windows_too_large_yields_nothing() {
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_elem([2us, 2us], 0us);
+        assert_eq!(a.windows([3us, 2us]).count(), 0);
+    }
+
+    #[test]
+    fn map_windows_sums_each_window() {
+        // Same 2x3 ramp as above; summing each 2x2 window gives a 1x2 array.
+        let a: MDArrayBuf<usize, N2> = MDArrayBuf::from_fn([2us, 3us], |ix| ix[0] * 3 + ix[1]);
+        let sums = a.map_windows([2us, 2us], |w| w.iter().fold(0us, |acc, &x| acc + x));
+        assert_eq!(sums.shape(), [1us, 2us]);
+        assert_eq!(*sums.get([0us, 0us]).unwrap(), 8us);
+        assert_eq!(*sums.get([0us, 1us]).unwrap(), 12us);
+    }
+}
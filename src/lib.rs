@@ -37,4 +37,4 @@ pub use typenat::{ N0,  N1,  N2,  N3,  N4,  N5,  N6,  N7,  N8,  N9,
                   N10, N11, N12, N13, N14, N15, N16, N17, N18, N19,
                   N20, N21, N22, N23, N24, N25, N26, N27, N28, N29,
                   N30, N31, N32, Nat, PosNat};
-pub use array::{MDArrayBuf, MDArrayView};
+pub use array::{IntoIter, Iter, MDArray, MDArrayBuf, MDArrayInline, MDArrayView, Storage, Windows};